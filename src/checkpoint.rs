@@ -0,0 +1,159 @@
+use std::collections::HashMap;
+use std::path::PathBuf;
+use std::sync::Arc;
+
+use async_trait::async_trait;
+use bb8_postgres::PostgresConnectionManager;
+use tokio::sync::Mutex;
+use tokio_postgres::NoTls;
+
+/// Persists the last successfully processed sequence number per `(stream, shard)` so
+/// that consumption can resume after a restart instead of always starting from `LATEST`.
+#[async_trait]
+pub trait CheckpointStore: Send + Sync {
+    async fn load(&self, stream_name: &str, shard_id: &str) -> anyhow::Result<Option<String>>;
+
+    async fn commit(
+        &self,
+        stream_name: &str,
+        shard_id: &str,
+        sequence_number: &str,
+    ) -> anyhow::Result<()>;
+}
+
+/// Builds the checkpoint store named by a `file://` or `postgres://` URI.
+pub async fn from_uri(uri: &str) -> anyhow::Result<Box<dyn CheckpointStore>> {
+    if let Some(path) = uri.strip_prefix("file://") {
+        return Ok(Box::new(FileCheckpointStore::new(path.into())));
+    }
+    if uri.starts_with("postgres://") || uri.starts_with("postgresql://") {
+        return Ok(Box::new(PostgresCheckpointStore::connect(uri).await?));
+    }
+    anyhow::bail!("unsupported `--checkpoint` URI `{}`, expected `file://` or `postgres://`", uri);
+}
+
+/// Stores checkpoints as `<stream_name>\t<shard_id>\t<sequence_number>` lines in a single file.
+pub struct FileCheckpointStore {
+    path: PathBuf,
+    // The file is small and rewritten wholesale on every commit, so a single lock is enough.
+    lock: Mutex<()>,
+}
+
+impl FileCheckpointStore {
+    pub fn new(path: PathBuf) -> Self {
+        Self {
+            path,
+            lock: Mutex::new(()),
+        }
+    }
+
+    fn key(stream_name: &str, shard_id: &str) -> String {
+        format!("{}\t{}", stream_name, shard_id)
+    }
+
+    async fn read_all(&self) -> anyhow::Result<HashMap<String, String>> {
+        let contents = match tokio::fs::read_to_string(&self.path).await {
+            Ok(contents) => contents,
+            Err(err) if err.kind() == std::io::ErrorKind::NotFound => return Ok(HashMap::new()),
+            Err(err) => return Err(err.into()),
+        };
+        let mut checkpoints = HashMap::new();
+        for line in contents.lines() {
+            if let Some((key, sequence_number)) = line.rsplit_once('\t') {
+                checkpoints.insert(key.to_string(), sequence_number.to_string());
+            }
+        }
+        Ok(checkpoints)
+    }
+}
+
+#[async_trait]
+impl CheckpointStore for FileCheckpointStore {
+    async fn load(&self, stream_name: &str, shard_id: &str) -> anyhow::Result<Option<String>> {
+        let checkpoints = self.read_all().await?;
+        Ok(checkpoints.get(&Self::key(stream_name, shard_id)).cloned())
+    }
+
+    async fn commit(
+        &self,
+        stream_name: &str,
+        shard_id: &str,
+        sequence_number: &str,
+    ) -> anyhow::Result<()> {
+        let _guard = self.lock.lock().await;
+        let mut checkpoints = self.read_all().await?;
+        checkpoints.insert(
+            Self::key(stream_name, shard_id),
+            sequence_number.to_string(),
+        );
+        let contents = checkpoints
+            .into_iter()
+            .map(|(key, sequence_number)| format!("{}\t{}\n", key, sequence_number))
+            .collect::<String>();
+        if let Some(parent) = self.path.parent() {
+            tokio::fs::create_dir_all(parent).await?;
+        }
+        tokio::fs::write(&self.path, contents).await?;
+        Ok(())
+    }
+}
+
+/// Stores `(stream_name, shard_id, sequence_number)` rows in a Postgres table via a
+/// pooled connection, so multiple consumer processes can share the same checkpoints.
+pub struct PostgresCheckpointStore {
+    pool: Arc<bb8::Pool<PostgresConnectionManager<NoTls>>>,
+}
+
+impl PostgresCheckpointStore {
+    pub async fn connect(uri: &str) -> anyhow::Result<Self> {
+        let manager = PostgresConnectionManager::new_from_stringlike(uri, NoTls)?;
+        let pool = bb8::Pool::builder().build(manager).await?;
+
+        let conn = pool.get().await?;
+        conn.batch_execute(
+            "CREATE TABLE IF NOT EXISTS streamctl_checkpoints (
+                stream_name TEXT NOT NULL,
+                shard_id TEXT NOT NULL,
+                sequence_number TEXT NOT NULL,
+                PRIMARY KEY (stream_name, shard_id)
+            )",
+        )
+        .await?;
+
+        Ok(Self {
+            pool: Arc::new(pool),
+        })
+    }
+}
+
+#[async_trait]
+impl CheckpointStore for PostgresCheckpointStore {
+    async fn load(&self, stream_name: &str, shard_id: &str) -> anyhow::Result<Option<String>> {
+        let conn = self.pool.get().await?;
+        let row = conn
+            .query_opt(
+                "SELECT sequence_number FROM streamctl_checkpoints \
+                 WHERE stream_name = $1 AND shard_id = $2",
+                &[&stream_name, &shard_id],
+            )
+            .await?;
+        Ok(row.map(|row| row.get(0)))
+    }
+
+    async fn commit(
+        &self,
+        stream_name: &str,
+        shard_id: &str,
+        sequence_number: &str,
+    ) -> anyhow::Result<()> {
+        let conn = self.pool.get().await?;
+        conn.execute(
+            "INSERT INTO streamctl_checkpoints (stream_name, shard_id, sequence_number) \
+             VALUES ($1, $2, $3) \
+             ON CONFLICT (stream_name, shard_id) DO UPDATE SET sequence_number = excluded.sequence_number",
+            &[&stream_name, &shard_id, &sequence_number],
+        )
+        .await?;
+        Ok(())
+    }
+}
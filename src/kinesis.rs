@@ -1,11 +1,20 @@
+use std::collections::BTreeSet;
+use std::sync::Arc;
 use std::time::Duration;
 
 use aws_config::meta::region::RegionProviderChain;
-use aws_sdk_kinesis::model::{PutRecordsRequestEntry, ShardIteratorType};
-use aws_sdk_kinesis::types::Blob;
+use aws_sdk_kinesis::model::{
+    PutRecordsRequestEntry, ScalingType, ShardIteratorType, StreamStatus,
+};
+use aws_sdk_kinesis::types::{Blob, DateTime};
 use aws_sdk_kinesis::{Client, Region};
+use aws_sdk_s3::Client as S3Client;
 use clap::{Args, Subcommand};
 use tokio::io::AsyncBufReadExt;
+use tokio::sync::mpsc;
+use tokio::time::Instant;
+
+use crate::checkpoint::{self, CheckpointStore};
 
 const ONE_MIB: usize = 1024 * 1024;
 
@@ -19,6 +28,25 @@ pub struct KinesisCommand {
 
 #[derive(Subcommand)]
 pub enum KinesisSubcommands {
+    /// Tail every shard from `TRIM_HORIZON` and write the records to S3 as
+    /// newline-delimited objects, for durable cold storage.
+    Archive {
+        #[clap(long)]
+        stream_name: String,
+        #[clap(long)]
+        bucket: String,
+        #[clap(long, default_value = "")]
+        prefix: String,
+        /// Roll a new object after this many records.
+        #[clap(long, default_value_t = 10_000)]
+        max_records: usize,
+        /// Roll a new object after this many bytes, even if `--max-records` isn't reached.
+        #[clap(long, default_value_t = 64 * ONE_MIB)]
+        max_bytes: usize,
+        /// Gzip-compress each archived object.
+        #[clap(long)]
+        gzip: bool,
+    },
     #[clap(alias = "mk")]
     Create {
         #[clap(long)]
@@ -38,23 +66,73 @@ pub enum KinesisSubcommands {
         #[clap(long)]
         stream_name: String,
     },
+    /// Replay records archived by `archive` back into a stream, via the same batching
+    /// path as `push`.
+    Replay {
+        #[clap(long)]
+        stream_name: String,
+        #[clap(long)]
+        bucket: String,
+        #[clap(long, default_value = "")]
+        prefix: String,
+    },
     Push {
         #[clap(long)]
         stream_name: String,
+        /// Number of `put_records` calls to keep in flight at once.
+        #[clap(long, default_value_t = std::thread::available_parallelism().map(|n| n.get()).unwrap_or(4))]
+        concurrency: usize,
     },
     ScaleUp {
         #[clap(long)]
         stream_name: String,
+        /// Desired open shard count. Overrides `--factor` when set.
+        #[clap(long)]
+        target_count: Option<i32>,
+        /// Multiplier applied to the current open shard count.
+        #[clap(long, default_value_t = 2)]
+        factor: i32,
     },
     ScaleDown {
         #[clap(long)]
         stream_name: String,
+        /// Desired open shard count. Overrides `--factor` when set.
+        #[clap(long)]
+        target_count: Option<i32>,
+        /// Divisor applied to the current open shard count.
+        #[clap(long, default_value_t = 2)]
+        factor: i32,
     },
     Tail {
         #[clap(long)]
         stream_name: String,
+        /// Tail a single shard. When omitted, all shards are tailed concurrently.
+        #[clap(long)]
+        shard_id: Option<usize>,
+        /// Where to start reading from: `latest`, `earliest`, `at-sequence <n>` or
+        /// `at-timestamp <rfc3339>`. Modeled on Kafka's `auto.offset.reset`.
+        #[clap(long, default_value = "latest")]
+        from: TailFrom,
+    },
+    /// Like `tail`, but resumes from a persisted checkpoint instead of always starting
+    /// from `--from` on restart.
+    Consume {
+        #[clap(long)]
+        stream_name: String,
+        #[clap(long)]
+        shard_id: Option<usize>,
+        #[clap(long, default_value = "latest")]
+        from: TailFrom,
+        /// Where to persist checkpoints, e.g. `file://./checkpoints.tsv` or `postgres://...`.
         #[clap(long)]
-        shard_id: usize,
+        checkpoint: String,
+    },
+    Verify {
+        #[clap(long)]
+        stream_name: String,
+        /// How long to keep draining shards for the expected records before giving up, in seconds.
+        #[clap(long, default_value_t = 30)]
+        timeout: u64,
     },
 }
 
@@ -110,19 +188,220 @@ async fn list_shards(client: &Client, stream_name: &str) -> anyhow::Result<()> {
     Ok(())
 }
 
-async fn tail(client: &Client, stream_name: &str, shard_id: usize) -> anyhow::Result<()> {
+#[derive(Clone)]
+enum TailFrom {
+    Latest,
+    Earliest,
+    AtSequenceNumber(String),
+    AtTimestamp(DateTime),
+}
+
+impl std::str::FromStr for TailFrom {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        if let Some(sequence_number) = s.strip_prefix("at-sequence ") {
+            return Ok(TailFrom::AtSequenceNumber(sequence_number.to_string()));
+        }
+        if let Some(timestamp) = s.strip_prefix("at-timestamp ") {
+            let date_time = DateTime::from_str(timestamp, aws_smithy_types::date_time::Format::DateTime)?;
+            return Ok(TailFrom::AtTimestamp(date_time));
+        }
+        match s {
+            "latest" => Ok(TailFrom::Latest),
+            "earliest" => Ok(TailFrom::Earliest),
+            other => anyhow::bail!(
+                "unrecognized `--from` value `{}`, expected `latest`, `earliest`, \
+                 `at-sequence <n>` or `at-timestamp <rfc3339>`",
+                other
+            ),
+        }
+    }
+}
+
+async fn tail(
+    client: &Client,
+    stream_name: &str,
+    shard_id: Option<usize>,
+    from: TailFrom,
+) -> anyhow::Result<()> {
+    let shard_ids = match shard_id {
+        Some(shard_id) => vec![make_shard_id(shard_id)],
+        None => {
+            let output = client.list_shards().stream_name(stream_name).send().await?;
+            output
+                .shards
+                .unwrap_or_default()
+                .into_iter()
+                .filter_map(|shard| shard.shard_id)
+                .collect()
+        }
+    };
+    let prefix_lines = shard_ids.len() > 1;
+
+    let (tx, mut rx) = mpsc::channel(1_024);
+    let mut handles = Vec::with_capacity(shard_ids.len());
+
+    for shard_id in shard_ids {
+        let client = client.clone();
+        let stream_name = stream_name.to_string();
+        let from = from.clone();
+        let tx = tx.clone();
+        handles.push(tokio::spawn(async move {
+            if let Err(err) = tail_shard(&client, &stream_name, &shard_id, from, prefix_lines, tx).await
+            {
+                eprintln!("Error tailing shard `{}`: {:#}", shard_id, err);
+            }
+        }));
+    }
+    drop(tx);
+
+    while let Some(line) = rx.recv().await {
+        println!("{}", line);
+    }
+    for handle in handles {
+        handle.await?;
+    }
+    Ok(())
+}
+
+async fn tail_shard(
+    client: &Client,
+    stream_name: &str,
+    shard_id: &str,
+    from: TailFrom,
+    prefix_lines: bool,
+    tx: mpsc::Sender<String>,
+) -> anyhow::Result<()> {
+    let mut request = client
+        .get_shard_iterator()
+        .stream_name(stream_name)
+        .shard_id(shard_id);
+    request = match from {
+        TailFrom::Latest => request.shard_iterator_type(ShardIteratorType::Latest),
+        TailFrom::Earliest => request.shard_iterator_type(ShardIteratorType::TrimHorizon),
+        TailFrom::AtSequenceNumber(sequence_number) => request
+            .shard_iterator_type(ShardIteratorType::AtSequenceNumber)
+            .starting_sequence_number(sequence_number),
+        TailFrom::AtTimestamp(timestamp) => request
+            .shard_iterator_type(ShardIteratorType::AtTimestamp)
+            .timestamp(timestamp),
+    };
+    let mut shard_iterator_opt = request.send().await?.shard_iterator;
+
+    let mut interval = tokio::time::interval(Duration::from_millis(205));
+
+    while let Some(shard_iterator) = shard_iterator_opt {
+        interval.tick().await;
+
+        let output = client
+            .get_records()
+            .shard_iterator(shard_iterator)
+            .send()
+            .await?;
+
+        if let Some(records) = output.records {
+            for record in records {
+                let line = record
+                    .data()
+                    .map(|blob| std::str::from_utf8(blob.as_ref()))
+                    .transpose()?
+                    .unwrap_or("Record payload is empty.");
+                let line = if prefix_lines {
+                    format!("[{}] {}", shard_id, line)
+                } else {
+                    line.to_string()
+                };
+                if tx.send(line).await.is_err() {
+                    return Ok(());
+                }
+            }
+        }
+        shard_iterator_opt = output.next_shard_iterator;
+    }
+    Ok(())
+}
+
+async fn verify(client: &Client, stream_name: &str, timeout: Duration) -> anyhow::Result<()> {
+    let stdin = tokio::io::stdin();
+    let reader = tokio::io::BufReader::new(stdin);
+    let mut lines = reader.lines();
+
+    let mut expected = BTreeSet::new();
+    while let Some(line) = lines.next_line().await? {
+        expected.insert(line);
+    }
+
+    let output = client.list_shards().stream_name(stream_name).send().await?;
+    let shards = output.shards.unwrap_or_default();
+
+    // Give every shard the same deadline and start them all at once, so one shard
+    // eating the whole `--timeout` budget can't starve the others.
+    let deadline = Instant::now() + timeout;
+    let mut handles = Vec::with_capacity(shards.len());
+    for shard in shards {
+        let client = client.clone();
+        let stream_name = stream_name.to_string();
+        let shard_id = shard.shard_id.unwrap();
+        handles.push(tokio::spawn(async move {
+            drain_shard(&client, &stream_name, &shard_id, deadline).await
+        }));
+    }
+
+    let mut observed = BTreeSet::new();
+    for handle in handles {
+        observed.extend(handle.await??);
+    }
+
+    let missing: Vec<_> = expected.difference(&observed).collect();
+    let unexpected: Vec<_> = observed.difference(&expected).collect();
+
+    if missing.is_empty() && unexpected.is_empty() {
+        println!(
+            "Verified {} record(s) on stream `{}`.",
+            expected.len(),
+            stream_name
+        );
+        return Ok(());
+    }
+
+    for record in &missing {
+        println!("Missing: {}", record);
+    }
+    for record in &unexpected {
+        println!("Unexpected: {}", record);
+    }
+    anyhow::bail!(
+        "Verification failed for stream `{}`: {} missing, {} unexpected.",
+        stream_name,
+        missing.len(),
+        unexpected.len()
+    );
+}
+
+async fn drain_shard(
+    client: &Client,
+    stream_name: &str,
+    shard_id: &str,
+    deadline: Instant,
+) -> anyhow::Result<Vec<String>> {
     let mut shard_iterator_opt = client
         .get_shard_iterator()
         .stream_name(stream_name)
-        .shard_id(make_shard_id(shard_id))
-        .shard_iterator_type(ShardIteratorType::Latest)
+        .shard_id(shard_id)
+        .shard_iterator_type(ShardIteratorType::TrimHorizon)
         .send()
         .await?
         .shard_iterator;
 
     let mut interval = tokio::time::interval(Duration::from_millis(205));
+    let mut lines = Vec::new();
 
     while let Some(shard_iterator) = shard_iterator_opt {
+        if Instant::now() >= deadline {
+            println!("Timed out draining shard `{}`.", shard_id);
+            break;
+        }
         interval.tick().await;
 
         let output = client
@@ -131,6 +410,9 @@ async fn tail(client: &Client, stream_name: &str, shard_id: usize) -> anyhow::Re
             .send()
             .await?;
 
+        let is_caught_up = output.millis_behind_latest.unwrap_or(0) == 0;
+        let is_empty = output.records.as_ref().map_or(true, |records| records.is_empty());
+
         if let Some(records) = output.records {
             for record in records {
                 let line = record
@@ -138,95 +420,654 @@ async fn tail(client: &Client, stream_name: &str, shard_id: usize) -> anyhow::Re
                     .map(|blob| std::str::from_utf8(blob.as_ref()))
                     .transpose()?
                     .unwrap_or("Record payload is empty.");
-                println!("{}", line);
+                lines.push(line.to_string());
             }
         }
         shard_iterator_opt = output.next_shard_iterator;
+
+        if is_empty && is_caught_up {
+            break;
+        }
+    }
+    Ok(lines)
+}
+
+async fn archive(
+    client: &Client,
+    s3_client: &S3Client,
+    stream_name: &str,
+    bucket: &str,
+    prefix: &str,
+    max_records: usize,
+    max_bytes: usize,
+    gzip: bool,
+) -> anyhow::Result<()> {
+    let output = client.list_shards().stream_name(stream_name).send().await?;
+    let shard_ids = output
+        .shards
+        .unwrap_or_default()
+        .into_iter()
+        .filter_map(|shard| shard.shard_id);
+
+    let mut handles = Vec::new();
+    for shard_id in shard_ids {
+        let client = client.clone();
+        let s3_client = s3_client.clone();
+        let stream_name = stream_name.to_string();
+        let bucket = bucket.to_string();
+        let prefix = prefix.to_string();
+        handles.push(tokio::spawn(async move {
+            archive_shard(
+                &client,
+                &s3_client,
+                &stream_name,
+                &shard_id,
+                &bucket,
+                &prefix,
+                max_records,
+                max_bytes,
+                gzip,
+            )
+            .await
+        }));
+    }
+    for handle in handles {
+        handle.await??;
     }
     Ok(())
 }
 
-async fn put_records(
+async fn archive_shard(
     client: &Client,
+    s3_client: &S3Client,
     stream_name: &str,
-    records: Vec<PutRecordsRequestEntry>,
+    shard_id: &str,
+    bucket: &str,
+    prefix: &str,
+    max_records: usize,
+    max_bytes: usize,
+    gzip: bool,
 ) -> anyhow::Result<()> {
-    client
-        .put_records()
-        .set_records(Some(records))
+    let mut shard_iterator_opt = client
+        .get_shard_iterator()
         .stream_name(stream_name)
+        .shard_id(shard_id)
+        .shard_iterator_type(ShardIteratorType::TrimHorizon)
+        .send()
+        .await?
+        .shard_iterator;
+
+    let mut interval = tokio::time::interval(Duration::from_millis(205));
+    let mut buffer: Vec<(String, String)> = Vec::new();
+    let mut buffered_bytes = 0usize;
+
+    while let Some(shard_iterator) = shard_iterator_opt {
+        interval.tick().await;
+
+        let output = client
+            .get_records()
+            .shard_iterator(shard_iterator)
+            .send()
+            .await?;
+
+        let is_caught_up = output.millis_behind_latest.unwrap_or(0) == 0;
+        let is_empty = output.records.as_ref().map_or(true, |records| records.is_empty());
+
+        if let Some(records) = output.records {
+            for record in records {
+                let line = record
+                    .data()
+                    .map(|blob| std::str::from_utf8(blob.as_ref()))
+                    .transpose()?
+                    .unwrap_or("Record payload is empty.");
+                buffered_bytes += line.len();
+                buffer.push((record.sequence_number.unwrap_or_default(), line.to_string()));
+
+                if buffer.len() >= max_records || buffered_bytes >= max_bytes {
+                    archive_object(
+                        s3_client,
+                        bucket,
+                        prefix,
+                        shard_id,
+                        std::mem::take(&mut buffer),
+                        gzip,
+                    )
+                    .await?;
+                    buffered_bytes = 0;
+                }
+            }
+        }
+        shard_iterator_opt = output.next_shard_iterator;
+
+        if is_empty && is_caught_up {
+            break;
+        }
+    }
+    if !buffer.is_empty() {
+        archive_object(s3_client, bucket, prefix, shard_id, buffer, gzip).await?;
+    }
+    Ok(())
+}
+
+async fn archive_object(
+    s3_client: &S3Client,
+    bucket: &str,
+    prefix: &str,
+    shard_id: &str,
+    records: Vec<(String, String)>,
+    gzip: bool,
+) -> anyhow::Result<()> {
+    let first_sequence_number = &records.first().unwrap().0;
+    let last_sequence_number = &records.last().unwrap().0;
+
+    let mut body = records
+        .iter()
+        .map(|(_, line)| line.as_str())
+        .collect::<Vec<_>>()
+        .join("\n");
+    body.push('\n');
+
+    let (body, extension) = if gzip {
+        use std::io::Write;
+        let mut encoder = flate2::write::GzEncoder::new(Vec::new(), flate2::Compression::default());
+        encoder.write_all(body.as_bytes())?;
+        (encoder.finish()?, "ndjson.gz")
+    } else {
+        (body.into_bytes(), "ndjson")
+    };
+    let num_records = records.len();
+
+    let prefix = prefix.trim_matches('/');
+    let key = if prefix.is_empty() {
+        format!(
+            "{}/{}-{}.{}",
+            shard_id, first_sequence_number, last_sequence_number, extension
+        )
+    } else {
+        format!(
+            "{}/{}/{}-{}.{}",
+            prefix, shard_id, first_sequence_number, last_sequence_number, extension
+        )
+    };
+    s3_client
+        .put_object()
+        .bucket(bucket)
+        .key(&key)
+        .body(body.into())
         .send()
         .await?;
+    println!(
+        "Archived {} record(s) to s3://{}/{}",
+        num_records, bucket, key
+    );
+    Ok(())
+}
+
+async fn replay(
+    client: &Client,
+    s3_client: &S3Client,
+    stream_name: &str,
+    bucket: &str,
+    prefix: &str,
+) -> anyhow::Result<()> {
+    let mut keys = Vec::new();
+    let mut continuation_token = None;
+    loop {
+        let mut request = s3_client.list_objects_v2().bucket(bucket).prefix(prefix);
+        if let Some(token) = continuation_token {
+            request = request.continuation_token(token);
+        }
+        let output = request.send().await?;
+        keys.extend(
+            output
+                .contents
+                .unwrap_or_default()
+                .into_iter()
+                .filter_map(|object| object.key),
+        );
+        continuation_token = output.next_continuation_token;
+        if continuation_token.is_none() {
+            break;
+        }
+    }
+    keys.sort();
+
+    let mut num_records = 0u64;
+    for key in &keys {
+        let output = s3_client.get_object().bucket(bucket).key(key).send().await?;
+        let bytes = output.body.collect().await?.into_bytes();
+        let contents = if key.ends_with(".gz") {
+            let mut decoder = flate2::read::GzDecoder::new(&bytes[..]);
+            let mut decompressed = String::new();
+            std::io::Read::read_to_string(&mut decoder, &mut decompressed)?;
+            decompressed
+        } else {
+            String::from_utf8(bytes.to_vec())?
+        };
+
+        let mut batcher = RecordBatcher::new(MAX_BYTES_PER_BATCH);
+        for line in contents.lines() {
+            let (entry, entry_bytes) = build_entry(line);
+            num_records += 1;
+            if let Some(batch) = batcher.add(entry, entry_bytes) {
+                put_records_with_retry(client, stream_name, batch).await?;
+            }
+        }
+        if let Some(batch) = batcher.finish() {
+            put_records_with_retry(client, stream_name, batch).await?;
+        }
+        println!("Replayed s3://{}/{}", bucket, key);
+    }
+    println!(
+        "Replayed {} record(s) into stream `{}`.",
+        num_records, stream_name
+    );
+    Ok(())
+}
+
+async fn consume(
+    client: &Client,
+    stream_name: &str,
+    shard_id: Option<usize>,
+    from: TailFrom,
+    checkpoint_uri: &str,
+) -> anyhow::Result<()> {
+    let store: Arc<dyn CheckpointStore> = Arc::from(checkpoint::from_uri(checkpoint_uri).await?);
+
+    let shard_ids = match shard_id {
+        Some(shard_id) => vec![make_shard_id(shard_id)],
+        None => {
+            let output = client.list_shards().stream_name(stream_name).send().await?;
+            output
+                .shards
+                .unwrap_or_default()
+                .into_iter()
+                .filter_map(|shard| shard.shard_id)
+                .collect()
+        }
+    };
+    let prefix_lines = shard_ids.len() > 1;
+
+    let (tx, mut rx) = mpsc::channel::<ConsumedRecord>(1_024);
+    let mut handles = Vec::with_capacity(shard_ids.len());
+
+    for shard_id in shard_ids {
+        let client = client.clone();
+        let stream_name = stream_name.to_string();
+        let from = from.clone();
+        let store = store.clone();
+        let tx = tx.clone();
+        handles.push(tokio::spawn(async move {
+            if let Err(err) =
+                consume_shard(&client, &stream_name, &shard_id, from, store.as_ref(), prefix_lines, tx)
+                    .await
+            {
+                eprintln!("Error consuming shard `{}`: {:#}", shard_id, err);
+            }
+        }));
+    }
+    drop(tx);
+
+    // Commit only once a record has actually been printed, so a crash between
+    // receiving and printing can't advance the checkpoint past un-printed records. Only
+    // the last record of each `get_records` batch is flagged for commit, so this is one
+    // checkpoint write per batch rather than per record.
+    while let Some(record) = rx.recv().await {
+        println!("{}", record.line);
+        if record.is_last_in_batch {
+            store
+                .commit(stream_name, &record.shard_id, &record.sequence_number)
+                .await?;
+        }
+    }
+    for handle in handles {
+        handle.await?;
+    }
     Ok(())
 }
 
-async fn push(client: &Client, stream_name: &str) -> anyhow::Result<()> {
+struct ConsumedRecord {
+    shard_id: String,
+    sequence_number: String,
+    line: String,
+    is_last_in_batch: bool,
+}
+
+async fn consume_shard(
+    client: &Client,
+    stream_name: &str,
+    shard_id: &str,
+    from: TailFrom,
+    store: &dyn CheckpointStore,
+    prefix_lines: bool,
+    tx: mpsc::Sender<ConsumedRecord>,
+) -> anyhow::Result<()> {
+    let mut request = client
+        .get_shard_iterator()
+        .stream_name(stream_name)
+        .shard_id(shard_id);
+    request = match store.load(stream_name, shard_id).await? {
+        // Deliberately `AfterSequenceNumber` rather than the `AtSequenceNumber` used for
+        // `--from at-sequence`: a committed checkpoint means the record was already
+        // processed, so resuming `At` that sequence number would redeliver it.
+        Some(sequence_number) => request
+            .shard_iterator_type(ShardIteratorType::AfterSequenceNumber)
+            .starting_sequence_number(sequence_number),
+        None => match from {
+            TailFrom::Latest => request.shard_iterator_type(ShardIteratorType::Latest),
+            TailFrom::Earliest => request.shard_iterator_type(ShardIteratorType::TrimHorizon),
+            TailFrom::AtSequenceNumber(sequence_number) => request
+                .shard_iterator_type(ShardIteratorType::AtSequenceNumber)
+                .starting_sequence_number(sequence_number),
+            TailFrom::AtTimestamp(timestamp) => request
+                .shard_iterator_type(ShardIteratorType::AtTimestamp)
+                .timestamp(timestamp),
+        },
+    };
+    let mut shard_iterator_opt = request.send().await?.shard_iterator;
+
+    let mut interval = tokio::time::interval(Duration::from_millis(205));
+
+    while let Some(shard_iterator) = shard_iterator_opt {
+        interval.tick().await;
+
+        let output = client
+            .get_records()
+            .shard_iterator(shard_iterator)
+            .send()
+            .await?;
+
+        if let Some(records) = output.records {
+            let last_index = records.len().saturating_sub(1);
+            for (index, record) in records.into_iter().enumerate() {
+                let sequence_number = match record.sequence_number.clone() {
+                    Some(sequence_number) => sequence_number,
+                    None => continue,
+                };
+                let line = record
+                    .data()
+                    .map(|blob| std::str::from_utf8(blob.as_ref()))
+                    .transpose()?
+                    .unwrap_or("Record payload is empty.");
+                let line = if prefix_lines {
+                    format!("[{}] {}", shard_id, line)
+                } else {
+                    line.to_string()
+                };
+                let record = ConsumedRecord {
+                    shard_id: shard_id.to_string(),
+                    sequence_number,
+                    line,
+                    is_last_in_batch: index == last_index,
+                };
+                if tx.send(record).await.is_err() {
+                    return Ok(());
+                }
+            }
+        }
+        shard_iterator_opt = output.next_shard_iterator;
+    }
+    Ok(())
+}
+
+const MAX_RECORDS_PER_BATCH: usize = 500;
+const MAX_BYTES_PER_BATCH: usize = 5 * ONE_MIB;
+const MAX_RETRY_BACKOFF: Duration = Duration::from_secs(5);
+const MAX_PUT_RECORDS_ATTEMPTS: u32 = 10;
+// Total payload we're willing to have buffered across all in-flight `put_records` calls.
+// Divided across `--concurrency` workers, this is what lets batch size adapt to both the
+// observed average record size and the worker count: more workers means smaller batches,
+// so throughput scales without any one worker holding an outsized chunk of memory. The
+// per-batch target is clamped to 1..=5 MiB, so above 64 workers the total exceeds this.
+const TARGET_IN_FLIGHT_BYTES: usize = 64 * ONE_MIB;
+
+fn target_batch_bytes(concurrency: usize) -> usize {
+    (TARGET_IN_FLIGHT_BYTES / concurrency.max(1)).clamp(ONE_MIB, MAX_BYTES_PER_BATCH)
+}
+
+/// Builds a `PutRecordsRequestEntry` from a line, along with its approximate wire size
+/// (payload + partition key, which both count toward the 5-MiB `PutRecords` limit).
+fn build_entry(line: &str) -> (PutRecordsRequestEntry, usize) {
+    let partition_key = format!("{:x}", seahash::hash(line.as_bytes()));
+    let entry_bytes = partition_key.len() + line.len();
+    let entry = PutRecordsRequestEntry::builder()
+        .partition_key(partition_key)
+        .data(Blob::new(line.to_string()))
+        .build();
+    (entry, entry_bytes)
+}
+
+/// Accumulates `PutRecordsRequestEntry` values into right-sized batches, shared by
+/// `push` and `replay`. Re-measures the average entry size once per flush (not per
+/// record) and uses it to adapt the next batch's target record count to `target_bytes`,
+/// while a hard check against the 500-record / 5-MiB `PutRecords` limits always applies.
+struct RecordBatcher {
+    entries: Vec<PutRecordsRequestEntry>,
+    bytes: usize,
+    target_bytes: usize,
+    target_records: usize,
+}
+
+impl RecordBatcher {
+    fn new(target_bytes: usize) -> Self {
+        Self {
+            entries: Vec::new(),
+            bytes: 0,
+            target_bytes,
+            target_records: MAX_RECORDS_PER_BATCH,
+        }
+    }
+
+    /// Appends `entry`, returning a batch to flush first if adding it would exceed the
+    /// hard `PutRecords` limits or the current adaptive target.
+    fn add(&mut self, entry: PutRecordsRequestEntry, entry_bytes: usize) -> Option<Vec<PutRecordsRequestEntry>> {
+        let must_flush = !self.entries.is_empty()
+            && (self.bytes + entry_bytes >= MAX_BYTES_PER_BATCH
+                || self.entries.len() + 1 > MAX_RECORDS_PER_BATCH
+                || self.bytes + entry_bytes >= self.target_bytes
+                || self.entries.len() + 1 > self.target_records);
+        let flushed = if must_flush { self.take() } else { None };
+        self.bytes += entry_bytes;
+        self.entries.push(entry);
+        flushed
+    }
+
+    /// Flushes whatever is currently buffered, if anything, adapting `target_records`
+    /// from the average entry size just observed.
+    fn take(&mut self) -> Option<Vec<PutRecordsRequestEntry>> {
+        if self.entries.is_empty() {
+            return None;
+        }
+        let avg_entry_bytes = (self.bytes / self.entries.len()).max(1);
+        self.target_records = (self.target_bytes / avg_entry_bytes).clamp(1, MAX_RECORDS_PER_BATCH);
+        self.bytes = 0;
+        Some(std::mem::take(&mut self.entries))
+    }
+
+    fn finish(mut self) -> Option<Vec<PutRecordsRequestEntry>> {
+        self.take()
+    }
+}
+
+async fn put_records_with_retry(
+    client: &Client,
+    stream_name: &str,
+    mut entries: Vec<PutRecordsRequestEntry>,
+) -> anyhow::Result<()> {
+    let mut backoff = Duration::from_millis(100);
+
+    for attempt in 1..=MAX_PUT_RECORDS_ATTEMPTS {
+        let output = client
+            .put_records()
+            .set_records(Some(entries.clone()))
+            .stream_name(stream_name)
+            .send()
+            .await?;
+
+        if output.failed_record_count.unwrap_or(0) == 0 {
+            return Ok(());
+        }
+
+        entries = entries
+            .into_iter()
+            .zip(output.records.unwrap_or_default())
+            .filter(|(_, result)| result.error_code.is_some())
+            .map(|(entry, _)| entry)
+            .collect();
+
+        if entries.is_empty() {
+            return Ok(());
+        }
+        if attempt == MAX_PUT_RECORDS_ATTEMPTS {
+            anyhow::bail!(
+                "giving up on {} record(s) for stream `{}` after {} attempts",
+                entries.len(),
+                stream_name,
+                attempt
+            );
+        }
+        tokio::time::sleep(backoff).await;
+        backoff = (backoff * 2).min(MAX_RETRY_BACKOFF);
+    }
+    Ok(())
+}
+
+fn spawn_batch(
+    client: &Client,
+    stream_name: &str,
+    entries: Vec<PutRecordsRequestEntry>,
+    semaphore: &Arc<tokio::sync::Semaphore>,
+) -> tokio::task::JoinHandle<anyhow::Result<()>> {
+    let client = client.clone();
+    let stream_name = stream_name.to_string();
+    let semaphore = semaphore.clone();
+    tokio::spawn(async move {
+        let _permit = semaphore.acquire_owned().await?;
+        put_records_with_retry(&client, &stream_name, entries).await
+    })
+}
+
+async fn push(client: &Client, stream_name: &str, concurrency: usize) -> anyhow::Result<()> {
     let stdin = tokio::io::stdin();
     let reader = tokio::io::BufReader::new(stdin);
     let mut lines = reader.lines();
 
-    let mut num_bytes = 0;
-    let mut num_records = 0;
-    let mut records = Vec::new();
+    let start = Instant::now();
+    let mut num_records: u64 = 0;
+    let mut num_bytes: u64 = 0;
+    let mut line_number: u64 = 0;
+
+    let semaphore = Arc::new(tokio::sync::Semaphore::new(concurrency.max(1)));
+    let mut in_flight = Vec::new();
+    let mut batcher = RecordBatcher::new(target_batch_bytes(concurrency));
 
     while let Some(line) = lines.next_line().await? {
         let record_len = line.len();
-        num_records += 1;
+        line_number += 1;
 
         if record_len > ONE_MIB {
-            println!("Record #{} is larger than 1 MiB, skipping.", num_records);
+            println!("Record #{} is larger than 1 MiB, skipping.", line_number);
             continue;
         }
-        if num_bytes + record_len > 5 * ONE_MIB {
-            put_records(
-                client,
-                stream_name,
-                std::mem::replace(&mut records, Vec::new()),
-            )
-            .await?;
-        }
-        num_bytes += record_len;
-
-        let record = PutRecordsRequestEntry::builder()
-            .partition_key(format!("{:x}", seahash::hash(line.as_bytes())))
-            .data(Blob::new(line))
-            .build();
-        records.push(record);
+        num_records += 1;
+        num_bytes += record_len as u64;
 
-        if records.len() == 500 {
-            put_records(
-                client,
-                stream_name,
-                std::mem::replace(&mut records, Vec::new()),
-            )
-            .await?;
-            num_bytes = 0;
+        let (entry, entry_bytes) = build_entry(&line);
+        if let Some(batch) = batcher.add(entry, entry_bytes) {
+            in_flight.push(spawn_batch(client, stream_name, batch, &semaphore));
         }
     }
-    if records.len() > 0 {
-        put_records(client, stream_name, records).await?;
+    if let Some(batch) = batcher.finish() {
+        in_flight.push(spawn_batch(client, stream_name, batch, &semaphore));
     }
+
+    for handle in in_flight {
+        handle.await??;
+    }
+
+    let elapsed_secs = start.elapsed().as_secs_f64().max(f64::EPSILON);
+    let mib_per_sec = (num_bytes as f64 / ONE_MIB as f64) / elapsed_secs;
     println!(
-        "Pushed {} records to stream `{}` in {}s ({} MiB/s).",
-        num_records, stream_name, "0", "0"
+        "Pushed {} records to stream `{}` in {:.2}s ({:.2} MiB/s).",
+        num_records, stream_name, elapsed_secs, mib_per_sec
     );
     Ok(())
 }
 
-async fn scale_up(client: &Client, stream_name: &str) -> anyhow::Result<()> {
-    // client
-    //     .split_shard()
-    //     .stream_name(stream_name)
-    Ok(())
+async fn open_shard_count(client: &Client, stream_name: &str) -> anyhow::Result<i32> {
+    let output = client
+        .describe_stream_summary()
+        .stream_name(stream_name)
+        .send()
+        .await?;
+    Ok(output
+        .stream_description_summary
+        .and_then(|summary| summary.open_shard_count)
+        .unwrap_or(1))
 }
 
-async fn scale_down(client: &Client, stream_name: &str) -> anyhow::Result<()> {
-    // client
-    //     .merge_shards()
-    //     .stream_name(stream_name)
+async fn update_shard_count(
+    client: &Client,
+    stream_name: &str,
+    target_shard_count: i32,
+) -> anyhow::Result<()> {
+    client
+        .update_shard_count()
+        .stream_name(stream_name)
+        .target_shard_count(target_shard_count)
+        .scaling_type(ScalingType::UniformScaling)
+        .send()
+        .await?;
+
+    loop {
+        let output = client
+            .describe_stream_summary()
+            .stream_name(stream_name)
+            .send()
+            .await?;
+        let summary = output.stream_description_summary.unwrap();
+        if summary.stream_status == Some(StreamStatus::Active) {
+            println!(
+                "Stream `{}` is now active with {} shard(s).",
+                stream_name,
+                summary.open_shard_count.unwrap_or(target_shard_count)
+            );
+            break;
+        }
+        println!("Stream `{}` is still UPDATING, waiting...", stream_name);
+        tokio::time::sleep(Duration::from_secs(2)).await;
+    }
     Ok(())
 }
 
+async fn scale_up(
+    client: &Client,
+    stream_name: &str,
+    target_count: Option<i32>,
+    factor: i32,
+) -> anyhow::Result<()> {
+    let target_shard_count = match target_count {
+        Some(target_count) => target_count,
+        None => open_shard_count(client, stream_name).await? * factor,
+    }
+    .max(1);
+    update_shard_count(client, stream_name, target_shard_count).await
+}
+
+async fn scale_down(
+    client: &Client,
+    stream_name: &str,
+    target_count: Option<i32>,
+    factor: i32,
+) -> anyhow::Result<()> {
+    let target_shard_count = match target_count {
+        Some(target_count) => target_count,
+        None => open_shard_count(client, stream_name).await? / factor,
+    }
+    .max(1);
+    update_shard_count(client, stream_name, target_shard_count).await
+}
+
 fn make_shard_id(id: usize) -> String {
     format!("shardId-{:0>12}", id)
 }
@@ -238,8 +1079,34 @@ impl KinesisCommand {
             .or_else(Region::new("us-east-1"));
         let config = aws_config::from_env().region(region_provider).load().await;
         let client = Client::new(&config);
+        let s3_client = S3Client::new(&config);
 
         match self.subcommand {
+            KinesisSubcommands::Archive {
+                stream_name,
+                bucket,
+                prefix,
+                max_records,
+                max_bytes,
+                gzip,
+            } => {
+                archive(
+                    &client,
+                    &s3_client,
+                    &stream_name,
+                    &bucket,
+                    &prefix,
+                    max_records,
+                    max_bytes,
+                    gzip,
+                )
+                .await?
+            }
+            KinesisSubcommands::Replay {
+                stream_name,
+                bucket,
+                prefix,
+            } => replay(&client, &s3_client, &stream_name, &bucket, &prefix).await?,
             KinesisSubcommands::Create {
                 stream_name,
                 num_shards,
@@ -251,15 +1118,35 @@ impl KinesisCommand {
             KinesisSubcommands::ListShards { stream_name } => {
                 list_shards(&client, &stream_name).await?
             }
-            KinesisSubcommands::Push { stream_name } => push(&client, &stream_name).await?,
-            KinesisSubcommands::ScaleUp { stream_name } => scale_up(&client, &stream_name).await?,
-            KinesisSubcommands::ScaleDown { stream_name } => {
-                scale_down(&client, &stream_name).await?
-            }
+            KinesisSubcommands::Push {
+                stream_name,
+                concurrency,
+            } => push(&client, &stream_name, concurrency).await?,
+            KinesisSubcommands::ScaleUp {
+                stream_name,
+                target_count,
+                factor,
+            } => scale_up(&client, &stream_name, target_count, factor).await?,
+            KinesisSubcommands::ScaleDown {
+                stream_name,
+                target_count,
+                factor,
+            } => scale_down(&client, &stream_name, target_count, factor).await?,
             KinesisSubcommands::Tail {
                 stream_name,
                 shard_id,
-            } => tail(&client, &stream_name, shard_id).await?,
+                from,
+            } => tail(&client, &stream_name, shard_id, from).await?,
+            KinesisSubcommands::Consume {
+                stream_name,
+                shard_id,
+                from,
+                checkpoint,
+            } => consume(&client, &stream_name, shard_id, from, &checkpoint).await?,
+            KinesisSubcommands::Verify {
+                stream_name,
+                timeout,
+            } => verify(&client, &stream_name, Duration::from_secs(timeout)).await?,
         };
         Ok(())
     }
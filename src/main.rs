@@ -1,8 +1,11 @@
 use clap::{Parser, Subcommand};
 
+mod checkpoint;
 mod kinesis;
+mod nats;
 
 use kinesis::KinesisCommand;
+use nats::NatsCommand;
 
 #[derive(Parser)]
 struct Cli {
@@ -14,6 +17,8 @@ struct Cli {
 enum Commands {
     #[clap(alias = "ki")]
     Kinesis(KinesisCommand),
+    #[clap(alias = "nc")]
+    Nats(NatsCommand),
 }
 
 #[tokio::main]
@@ -23,6 +28,7 @@ async fn main() -> anyhow::Result<()> {
     let cli = Cli::parse();
     match cli.command {
         Commands::Kinesis(subcommand) => subcommand.exec().await?,
+        Commands::Nats(subcommand) => subcommand.exec().await?,
     }
     Ok(())
 }
@@ -0,0 +1,155 @@
+use async_nats::jetstream::{self, stream::Config as StreamConfig};
+use clap::{Args, Subcommand};
+use futures::StreamExt;
+use tokio::io::AsyncBufReadExt;
+use tokio::time::Instant;
+
+const ONE_MIB: f64 = 1024.0 * 1024.0;
+
+#[derive(Args)]
+pub struct NatsCommand {
+    #[clap(global = true, long, default_value = "nats://localhost:4222")]
+    server: String,
+    #[clap(subcommand)]
+    subcommand: NatsSubcommands,
+}
+
+#[derive(Subcommand)]
+pub enum NatsSubcommands {
+    #[clap(alias = "mk")]
+    Create {
+        #[clap(long)]
+        stream_name: String,
+        #[clap(long)]
+        subjects: Vec<String>,
+    },
+    #[clap(alias = "rm")]
+    Delete {
+        #[clap(long)]
+        stream_name: String,
+    },
+    #[clap(alias = "ls")]
+    List,
+    Push {
+        #[clap(long)]
+        subject: String,
+    },
+    Tail {
+        #[clap(long)]
+        stream_name: String,
+        #[clap(long)]
+        consumer_name: String,
+    },
+}
+
+async fn create_stream(
+    jetstream: &jetstream::Context,
+    stream_name: &str,
+    subjects: Vec<String>,
+) -> anyhow::Result<()> {
+    jetstream
+        .create_stream(StreamConfig {
+            name: stream_name.to_string(),
+            subjects,
+            ..Default::default()
+        })
+        .await?;
+    println!("Created stream `{}`.", stream_name);
+    Ok(())
+}
+
+async fn delete_stream(jetstream: &jetstream::Context, stream_name: &str) -> anyhow::Result<()> {
+    jetstream.delete_stream(stream_name).await?;
+    println!("Deleted stream `{}` successfully.", stream_name);
+    Ok(())
+}
+
+async fn list_streams(jetstream: &jetstream::Context) -> anyhow::Result<()> {
+    let mut streams = jetstream.streams();
+    while let Some(stream) = streams.next().await {
+        println!("{}", stream?.config.name);
+    }
+    Ok(())
+}
+
+async fn push(jetstream: &jetstream::Context, subject: &str) -> anyhow::Result<()> {
+    let stdin = tokio::io::stdin();
+    let reader = tokio::io::BufReader::new(stdin);
+    let mut lines = reader.lines();
+
+    let start = Instant::now();
+    let mut num_messages: u64 = 0;
+    let mut num_bytes: u64 = 0;
+    let mut acks = Vec::new();
+
+    while let Some(line) = lines.next_line().await? {
+        num_messages += 1;
+        num_bytes += line.len() as u64;
+
+        let ack = jetstream.publish(subject.to_string(), line.into()).await?;
+        acks.push(ack);
+
+        if acks.len() == 500 {
+            for ack in acks.drain(..) {
+                ack.await?;
+            }
+        }
+    }
+    for ack in acks {
+        ack.await?;
+    }
+
+    let elapsed_secs = start.elapsed().as_secs_f64().max(f64::EPSILON);
+    let mib_per_sec = (num_bytes as f64 / ONE_MIB) / elapsed_secs;
+    println!(
+        "Published {} message(s) to subject `{}` in {:.2}s ({:.2} MiB/s).",
+        num_messages, subject, elapsed_secs, mib_per_sec
+    );
+    Ok(())
+}
+
+async fn tail(
+    jetstream: &jetstream::Context,
+    stream_name: &str,
+    consumer_name: &str,
+) -> anyhow::Result<()> {
+    let consumer: jetstream::consumer::PullConsumer = jetstream
+        .get_consumer_from_stream(consumer_name, stream_name)
+        .await?;
+    let mut messages = consumer.messages().await?;
+
+    while let Some(message) = messages.next().await {
+        let message = message?;
+        let line = std::str::from_utf8(&message.payload)?;
+        println!("{}", line);
+        message
+            .ack()
+            .await
+            .map_err(|err| anyhow::anyhow!("failed to ack message: {}", err))?;
+    }
+    Ok(())
+}
+
+impl NatsCommand {
+    pub async fn exec(self) -> anyhow::Result<()> {
+        let client = async_nats::connect(&self.server).await?;
+        let jetstream = jetstream::new(client);
+
+        match self.subcommand {
+            NatsSubcommands::Create {
+                stream_name,
+                subjects,
+            } => create_stream(&jetstream, &stream_name, subjects).await?,
+            NatsSubcommands::Delete { stream_name } => {
+                delete_stream(&jetstream, &stream_name).await?
+            }
+            NatsSubcommands::List => list_streams(&jetstream).await?,
+            NatsSubcommands::Push { subject } => push(&jetstream, &subject).await?,
+            NatsSubcommands::Tail {
+                stream_name,
+                consumer_name,
+            } => tail(&jetstream, &stream_name, &consumer_name).await?,
+        };
+        Ok(())
+    }
+}